@@ -0,0 +1,28 @@
+use std::error::Error;
+use std::fmt;
+
+/// Error type for kvs.
+#[derive(Debug, PartialEq, Eq)]
+pub enum KvsError {
+    /// The key does not exist.
+    KeyNotFound,
+    /// The stored value is not an integer.
+    NotANumber,
+    /// The result of an arithmetic operation does not fit in an `i64`.
+    Overflow,
+}
+
+impl fmt::Display for KvsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KvsError::KeyNotFound => write!(f, "Key not found"),
+            KvsError::NotANumber => write!(f, "Value is not an integer"),
+            KvsError::Overflow => write!(f, "Integer overflow"),
+        }
+    }
+}
+
+impl Error for KvsError {}
+
+/// Result type for kvs.
+pub type Result<T> = std::result::Result<T, KvsError>;
@@ -5,6 +5,15 @@
 //! A library for storing key/values.
 
 use std::collections::HashMap;
+use std::ops::RangeBounds;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+
+pub use error::{KvsError, Result};
+
+mod error;
 
 /// The `KvStore` stores key/values in memory.
 ///
@@ -20,6 +29,100 @@ use std::collections::HashMap;
 #[derive(Default)]
 pub struct KvStore {
     map: HashMap<String, String>,
+    watchers: Mutex<Vec<Watcher>>,
+    counters: Counters,
+}
+
+/// Operation counts returned by `KvStore::counters`.
+///
+/// Each key-level access to the store is counted once. A read-modify-write
+/// such as `increment` counts one get and one set, and `rename` counts one
+/// remove and one set. `drain` and `retain` count one remove per key they
+/// remove; their scan of the keys is not counted, and neither are scans.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EngineCounters {
+    /// Number of key lookups, hits and misses alike.
+    pub gets: u64,
+    /// Number of values written.
+    pub sets: u64,
+    /// Number of key removals, hits and misses alike.
+    pub removes: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    gets: AtomicU64,
+    sets: AtomicU64,
+    removes: AtomicU64,
+}
+
+/// The kind of change carried by a `WatchEvent`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    /// The key was set to a new value.
+    Set,
+    /// The key was removed.
+    Remove,
+}
+
+/// A change to a watched key, sent to the receivers returned by `KvStore::watch`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchEvent {
+    /// The key that changed.
+    pub key: String,
+    /// Whether the key was set or removed.
+    pub kind: WatchKind,
+    /// The new value for `Set`, `None` for `Remove`.
+    pub value: Option<String>,
+}
+
+/// Whether `KvStore::set_detailed` created or updated a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOutcome {
+    /// The key did not exist before.
+    Created,
+    /// The key existed and its value was replaced.
+    Updated {
+        /// Byte length of the replaced value.
+        previous_len: u64,
+    },
+}
+
+/// One page of key/values returned by `KvStore::scan_paginated`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanPage {
+    /// The key/values in this page, sorted by key.
+    pub entries: Vec<(String, String)>,
+    /// Pass as `start_after` to fetch the next page, `None` if there is none.
+    pub next_cursor: Option<String>,
+}
+
+/// A read-only view of a `KvStore` as of the time `KvStore::snapshot` was called.
+///
+/// Later writes to the store are not visible through the snapshot.
+pub struct Snapshot {
+    map: HashMap<String, String>,
+}
+
+impl Snapshot {
+    /// Get the string value of a string key as of snapshot time.
+    ///
+    /// If the key did not exist, return `None`.
+    pub fn get(&self, key: String) -> Option<String> {
+        self.get_ref(&key)
+    }
+
+    /// Get the string value of a borrowed string key as of snapshot time.
+    ///
+    /// Same as `get`, without requiring an owned key.
+    pub fn get_ref(&self, key: &str) -> Option<String> {
+        self.map.get(key).cloned()
+    }
+}
+
+struct Watcher {
+    prefix: String,
+    sender: Sender<WatchEvent>,
 }
 
 impl KvStore {
@@ -27,6 +130,8 @@ impl KvStore {
     pub fn new() -> KvStore {
         KvStore {
             map: HashMap::new(),
+            watchers: Mutex::new(Vec::new()),
+            counters: Counters::default(),
         }
     }
 
@@ -34,18 +139,282 @@ impl KvStore {
     ///
     /// If the key exists, the value is updated.
     pub fn set(&mut self, key: String, value: String) {
+        self.counters.sets.fetch_add(1, Ordering::Relaxed);
+        let watchers = self.watchers.get_mut().unwrap();
+        if !watchers
+            .iter()
+            .any(|watcher| key.starts_with(&watcher.prefix))
+        {
+            self.map.insert(key, value);
+            return;
+        }
+        let watched_key = key.clone();
         self.map.insert(key, value);
+        let value = &self.map[&watched_key];
+        notify(watchers, &watched_key, WatchKind::Set, Some(value));
+    }
+
+    /// Set the value of a string key to a string, reporting whether the key
+    /// was created or updated.
+    pub fn set_detailed(&mut self, key: String, value: String) -> SetOutcome {
+        let outcome = match self.value_len(&key) {
+            Some(previous_len) => SetOutcome::Updated { previous_len },
+            None => SetOutcome::Created,
+        };
+        self.set(key, value);
+        outcome
     }
 
     /// Get the string value of the a string key.
     ///
     /// If the key does not exist, return `None`.
     pub fn get(&self, key: String) -> Option<String> {
-        self.map.get(&key).cloned()
+        self.get_ref(&key)
+    }
+
+    /// Get the string value of a borrowed string key.
+    ///
+    /// Same as `get`, without requiring an owned key.
+    pub fn get_ref(&self, key: &str) -> Option<String> {
+        self.lookup(key).cloned()
+    }
+
+    /// Copy the value of a string key into `buf`, reusing its allocation.
+    ///
+    /// `buf` is cleared first. Return whether the key exists.
+    pub fn get_into(&self, key: &str, buf: &mut Vec<u8>) -> bool {
+        buf.clear();
+        match self.lookup(key) {
+            Some(value) => {
+                buf.extend_from_slice(value.as_bytes());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Check whether a borrowed string key exists.
+    pub fn contains_ref(&self, key: &str) -> bool {
+        self.lookup(key).is_some()
+    }
+
+    /// Get the string value of a string key, or `default` if it is absent.
+    pub fn get_or(&self, key: String, default: String) -> String {
+        self.get_ref(&key).unwrap_or(default)
+    }
+
+    /// Get the string value of a string key.
+    ///
+    /// If the key does not exist, `default` is stored and returned.
+    pub fn get_or_insert(&mut self, key: String, default: String) -> String {
+        if let Some(value) = self.lookup(&key) {
+            return value.clone();
+        }
+        self.set(key, default.clone());
+        default
+    }
+
+    /// Get the byte length of the value of a string key.
+    ///
+    /// If the key does not exist, return `None`.
+    pub fn value_len(&self, key: &str) -> Option<u64> {
+        self.lookup(key).map(|value| value.len() as u64)
     }
 
     /// Remove a given key.
     pub fn remove(&mut self, key: String) {
-        self.map.remove(&key);
+        self.remove_entry(&key);
+    }
+
+    /// Remove several keys.
+    ///
+    /// Returns, for each key in order, whether it existed and was removed.
+    pub fn remove_batch(&mut self, keys: Vec<String>) -> Vec<bool> {
+        keys.into_iter()
+            .map(|key| self.remove_entry(&key).is_some())
+            .collect()
+    }
+
+    /// Remove all keys in `range` and return them with their values.
+    ///
+    /// The returned pairs are sorted by key.
+    pub fn drain<R: RangeBounds<String>>(&mut self, range: R) -> Vec<(String, String)> {
+        let mut keys: Vec<String> = self
+            .map
+            .keys()
+            .filter(|key| range.contains(key))
+            .cloned()
+            .collect();
+        keys.sort();
+        keys.into_iter()
+            .filter_map(|key| self.remove_entry(&key).map(|value| (key, value)))
+            .collect()
+    }
+
+    /// Get up to `limit` key/values in `range` whose keys come after
+    /// `start_after`, sorted by key.
+    pub fn scan_paginated<R: RangeBounds<String>>(
+        &self,
+        range: R,
+        start_after: Option<String>,
+        limit: usize,
+    ) -> ScanPage {
+        let mut remaining = self
+            .sorted_range(range)
+            .into_iter()
+            .filter(|(key, _)| start_after.as_ref().is_none_or(|after| *key > after))
+            .peekable();
+        let entries: Vec<(String, String)> = remaining
+            .by_ref()
+            .take(limit)
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        let next_cursor = match remaining.peek() {
+            Some(_) => entries.last().map(|(key, _)| key.clone()).or(start_after),
+            None => None,
+        };
+        ScanPage {
+            entries,
+            next_cursor,
+        }
+    }
+
+    /// Iterate over the key/values in `range` from the highest key down.
+    ///
+    /// Values are cloned as the iterator advances.
+    pub fn scan_rev<R: RangeBounds<String>>(
+        &self,
+        range: R,
+    ) -> impl Iterator<Item = (String, String)> + '_ {
+        self.sorted_range(range)
+            .into_iter()
+            .rev()
+            .map(|(key, value)| (key.clone(), value.clone()))
+    }
+
+    /// Keep only the key/values for which `keep` returns `true`.
+    pub fn retain<F: FnMut(&str, &str) -> bool>(&mut self, mut keep: F) {
+        let dropped: Vec<String> = self
+            .map
+            .iter()
+            .filter(|(key, value)| !keep(key, value))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in dropped {
+            self.remove_entry(&key);
+        }
+    }
+
+    /// Rename a key, keeping its value.
+    ///
+    /// If `to` exists, its value is overwritten. If `from` does not exist,
+    /// return `KvsError::KeyNotFound` and leave the store unchanged.
+    pub fn rename(&mut self, from: String, to: String) -> Result<()> {
+        if from == to {
+            return self.lookup(&from).map(|_| ()).ok_or(KvsError::KeyNotFound);
+        }
+        let value = self.remove_entry(&from).ok_or(KvsError::KeyNotFound)?;
+        self.set(to, value);
+        Ok(())
     }
+
+    /// Add `delta` to the integer value of a string key and return the result.
+    ///
+    /// An absent key counts as `0`. If the stored value is not an `i64`, return
+    /// `KvsError::NotANumber`; if the sum overflows, return `KvsError::Overflow`.
+    pub fn increment(&mut self, key: String, delta: i64) -> Result<i64> {
+        let current = match self.lookup(&key) {
+            Some(value) => value.parse::<i64>().map_err(|_| KvsError::NotANumber)?,
+            None => 0,
+        };
+        let result = current.checked_add(delta).ok_or(KvsError::Overflow)?;
+        self.set(key, result.to_string());
+        Ok(result)
+    }
+
+    /// Take a consistent read-only snapshot of the store.
+    ///
+    /// The snapshot copies the current key/values, so it costs memory
+    /// proportional to the store size.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            map: self.map.clone(),
+        }
+    }
+
+    /// Get the number of operations performed since creation or the last
+    /// `reset_counters`.
+    pub fn counters(&self) -> EngineCounters {
+        EngineCounters {
+            gets: self.counters.gets.load(Ordering::Relaxed),
+            sets: self.counters.sets.load(Ordering::Relaxed),
+            removes: self.counters.removes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Reset all operation counters to zero.
+    pub fn reset_counters(&self) {
+        self.counters.gets.store(0, Ordering::Relaxed);
+        self.counters.sets.store(0, Ordering::Relaxed);
+        self.counters.removes.store(0, Ordering::Relaxed);
+    }
+
+    /// Watch the keys starting with `prefix`.
+    ///
+    /// Every set, and every remove of an existing key, that matches the prefix
+    /// is sent to the returned `Receiver` once it has been applied, in the
+    /// order it happened. Each call returns its own receiver; dropping it
+    /// unregisters the watcher on the next matching write.
+    pub fn watch(&self, prefix: String) -> Receiver<WatchEvent> {
+        let (sender, receiver) = unbounded();
+        self.watchers
+            .lock()
+            .unwrap()
+            .push(Watcher { prefix, sender });
+        receiver
+    }
+
+    /// Collect the key/values in `range`, sorted by key.
+    fn sorted_range<R: RangeBounds<String>>(&self, range: R) -> Vec<(&String, &String)> {
+        let mut entries: Vec<(&String, &String)> = self
+            .map
+            .iter()
+            .filter(|(key, _)| range.contains(*key))
+            .collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        entries
+    }
+
+    /// Look up a key, counting it as a get.
+    fn lookup(&self, key: &str) -> Option<&String> {
+        self.counters.gets.fetch_add(1, Ordering::Relaxed);
+        self.map.get(key)
+    }
+
+    /// Remove a key, notifying watchers if it existed, and return its value.
+    fn remove_entry(&mut self, key: &str) -> Option<String> {
+        self.counters.removes.fetch_add(1, Ordering::Relaxed);
+        let value = self.map.remove(key)?;
+        notify(
+            self.watchers.get_mut().unwrap(),
+            key,
+            WatchKind::Remove,
+            None,
+        );
+        Some(value)
+    }
+}
+
+fn notify(watchers: &mut Vec<Watcher>, key: &str, kind: WatchKind, value: Option<&str>) {
+    watchers.retain(|watcher| {
+        if !key.starts_with(&watcher.prefix) {
+            return true;
+        }
+        let event = WatchEvent {
+            key: key.to_owned(),
+            kind,
+            value: value.map(str::to_owned),
+        };
+        watcher.sender.send(event).is_ok()
+    });
 }
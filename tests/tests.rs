@@ -1,5 +1,5 @@
 use assert_cmd::prelude::*;
-use kvs::KvStore;
+use kvs::{EngineCounters, KvStore, KvsError, ScanPage, SetOutcome, WatchEvent, WatchKind};
 use predicates::str::contains;
 use std::process::Command;
 
@@ -14,7 +14,7 @@ fn cli_no_args() {
 fn cli_version() {
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["-V"])
+        .args(["-V"])
         .assert()
         .stdout(contains(env!("CARGO_PKG_VERSION")));
 }
@@ -24,7 +24,7 @@ fn cli_version() {
 fn cli_get() {
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["get", "key1"])
+        .args(["get", "key1"])
         .assert()
         .failure()
         .stderr(contains("unimplemented"));
@@ -35,7 +35,7 @@ fn cli_get() {
 fn cli_set() {
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["set", "key1", "value1"])
+        .args(["set", "key1", "value1"])
         .assert()
         .failure()
         .stderr(contains("unimplemented"));
@@ -46,7 +46,7 @@ fn cli_set() {
 fn cli_rm() {
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["rm", "key1"])
+        .args(["rm", "key1"])
         .assert()
         .failure()
         .stderr(contains("unimplemented"));
@@ -56,13 +56,13 @@ fn cli_rm() {
 fn cli_invalid_get() {
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["get"])
+        .args(["get"])
         .assert()
         .failure();
 
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["get", "extra", "field"])
+        .args(["get", "extra", "field"])
         .assert()
         .failure();
 }
@@ -71,19 +71,19 @@ fn cli_invalid_get() {
 fn cli_invalid_set() {
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["set"])
+        .args(["set"])
         .assert()
         .failure();
 
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["set", "missing_field"])
+        .args(["set", "missing_field"])
         .assert()
         .failure();
 
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["set", "extra", "extra", "field"])
+        .args(["set", "extra", "extra", "field"])
         .assert()
         .failure();
 }
@@ -92,13 +92,13 @@ fn cli_invalid_set() {
 fn cli_invalid_rm() {
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["rm"])
+        .args(["rm"])
         .assert()
         .failure();
 
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["rm", "extra", "field"])
+        .args(["rm", "extra", "field"])
         .assert()
         .failure();
 }
@@ -107,7 +107,7 @@ fn cli_invalid_rm() {
 fn cli_invalid_subcommand() {
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["unknown", "subcommand"])
+        .args(["unknown", "subcommand"])
         .assert()
         .failure();
 }
@@ -153,3 +153,576 @@ fn remove_key() {
     store.remove("key1".to_owned());
     assert_eq!(store.get("key1".to_owned()), None);
 }
+
+// Should get the stored value instead of the default
+#[test]
+fn get_or_present_value() {
+    let mut store = KvStore::new();
+
+    store.set("key1".to_owned(), "value1".to_owned());
+    assert_eq!(
+        store.get_or("key1".to_owned(), "default".to_owned()),
+        "value1".to_owned()
+    );
+}
+
+// Should get the default when the key is absent, without storing it
+#[test]
+fn get_or_absent_value() {
+    let store = KvStore::new();
+
+    assert_eq!(
+        store.get_or("key1".to_owned(), "default".to_owned()),
+        "default".to_owned()
+    );
+    assert_eq!(store.get("key1".to_owned()), None);
+}
+
+// Should store the default on a miss and keep an existing value on a hit
+#[test]
+fn get_or_insert_value() {
+    let mut store = KvStore::new();
+
+    assert_eq!(
+        store.get_or_insert("key1".to_owned(), "default".to_owned()),
+        "default".to_owned()
+    );
+    assert_eq!(store.get("key1".to_owned()), Some("default".to_owned()));
+
+    assert_eq!(
+        store.get_or_insert("key1".to_owned(), "other".to_owned()),
+        "default".to_owned()
+    );
+    assert_eq!(store.get("key1".to_owned()), Some("default".to_owned()));
+}
+
+// Should only receive events for keys matching the watched prefix, in order
+#[test]
+fn watch_prefix() {
+    let mut store = KvStore::new();
+    let events = store.watch("a".to_owned());
+
+    store.set("a1".to_owned(), "value1".to_owned());
+    store.set("b1".to_owned(), "value2".to_owned());
+    store.set("a2".to_owned(), "value3".to_owned());
+    store.remove("b1".to_owned());
+    store.remove("a1".to_owned());
+    store.remove("a3".to_owned());
+
+    let received: Vec<WatchEvent> = events.try_iter().collect();
+    assert_eq!(
+        received,
+        vec![
+            WatchEvent {
+                key: "a1".to_owned(),
+                kind: WatchKind::Set,
+                value: Some("value1".to_owned()),
+            },
+            WatchEvent {
+                key: "a2".to_owned(),
+                kind: WatchKind::Set,
+                value: Some("value3".to_owned()),
+            },
+            WatchEvent {
+                key: "a1".to_owned(),
+                kind: WatchKind::Remove,
+                value: None,
+            },
+        ]
+    );
+}
+
+// Each watcher should get its own copy of the events
+#[test]
+fn watch_multiple_receivers() {
+    let mut store = KvStore::new();
+    let first = store.watch("key".to_owned());
+    let second = store.watch("key".to_owned());
+
+    store.set("key1".to_owned(), "value1".to_owned());
+
+    assert_eq!(first.try_iter().count(), 1);
+    assert_eq!(second.try_iter().count(), 1);
+}
+
+// Dropping one receiver should not stop events to the others
+#[test]
+fn watch_dropped_receiver() {
+    let mut store = KvStore::new();
+    let first = store.watch("key".to_owned());
+    let second = store.watch("key".to_owned());
+
+    drop(first);
+    store.set("key1".to_owned(), "value1".to_owned());
+    store.set("key2".to_owned(), "value2".to_owned());
+
+    assert_eq!(second.try_iter().count(), 2);
+}
+
+// Should get the byte length of the stored value
+#[test]
+fn value_len() {
+    let mut store = KvStore::new();
+
+    store.set("key1".to_owned(), "value1".to_owned());
+    store.set("key2".to_owned(), "".to_owned());
+    store.set("key3".to_owned(), "värde".to_owned());
+
+    for key in &["key1", "key2", "key3"] {
+        let value = store.get((*key).to_owned()).unwrap();
+        assert_eq!(store.value_len(key), Some(value.len() as u64));
+    }
+    assert_eq!(store.value_len("key4"), None);
+}
+
+// Should move the value to the new key
+#[test]
+fn rename_key() {
+    let mut store = KvStore::new();
+
+    store.set("key1".to_owned(), "value1".to_owned());
+    store.rename("key1".to_owned(), "key2".to_owned()).unwrap();
+
+    assert_eq!(store.get("key1".to_owned()), None);
+    assert_eq!(store.get("key2".to_owned()), Some("value1".to_owned()));
+}
+
+// Should overwrite the destination key
+#[test]
+fn rename_overwrite_destination() {
+    let mut store = KvStore::new();
+
+    store.set("key1".to_owned(), "value1".to_owned());
+    store.set("key2".to_owned(), "value2".to_owned());
+    store.rename("key1".to_owned(), "key2".to_owned()).unwrap();
+
+    assert_eq!(store.get("key1".to_owned()), None);
+    assert_eq!(store.get("key2".to_owned()), Some("value1".to_owned()));
+}
+
+// Should fail and leave the store unchanged when the source key is absent
+#[test]
+fn rename_non_existent_key() {
+    let mut store = KvStore::new();
+
+    store.set("key2".to_owned(), "value2".to_owned());
+    assert_eq!(
+        store.rename("key1".to_owned(), "key2".to_owned()),
+        Err(KvsError::KeyNotFound)
+    );
+    assert_eq!(store.get("key2".to_owned()), Some("value2".to_owned()));
+}
+
+// Should keep the value when renaming a key to itself
+#[test]
+fn rename_to_self() {
+    let mut store = KvStore::new();
+
+    store.set("key1".to_owned(), "value1".to_owned());
+    store.rename("key1".to_owned(), "key1".to_owned()).unwrap();
+
+    assert_eq!(store.get("key1".to_owned()), Some("value1".to_owned()));
+}
+
+// Should get and check keys through borrowed `&str` keys
+#[test]
+fn get_ref_and_contains_ref() {
+    let mut store = KvStore::new();
+
+    store.set("key1".to_owned(), "value1".to_owned());
+
+    assert_eq!(store.get_ref("key1"), Some("value1".to_owned()));
+    assert_eq!(store.get_ref("key2"), None);
+    assert!(store.contains_ref("key1"));
+    assert!(!store.contains_ref("key2"));
+}
+
+// Should add to an integer value, treating an absent key as 0
+#[test]
+fn increment_value() {
+    let mut store = KvStore::new();
+
+    assert_eq!(store.increment("key1".to_owned(), 5), Ok(5));
+    assert_eq!(store.increment("key1".to_owned(), -7), Ok(-2));
+    assert_eq!(store.get("key1".to_owned()), Some("-2".to_owned()));
+}
+
+// Should fail and leave the value unchanged when it is not an integer
+#[test]
+fn increment_non_integer_value() {
+    let mut store = KvStore::new();
+
+    store.set("key1".to_owned(), "value1".to_owned());
+    assert_eq!(
+        store.increment("key1".to_owned(), 1),
+        Err(KvsError::NotANumber)
+    );
+    assert_eq!(store.get("key1".to_owned()), Some("value1".to_owned()));
+
+    store.set("key2".to_owned(), i64::MAX.to_string());
+    assert_eq!(
+        store.increment("key2".to_owned(), 1),
+        Err(KvsError::Overflow)
+    );
+    assert_eq!(store.get("key2".to_owned()), Some(i64::MAX.to_string()));
+}
+
+// Should reject results below `i64::MIN`
+#[test]
+fn increment_underflow() {
+    let mut store = KvStore::new();
+
+    store.set("key1".to_owned(), i64::MIN.to_string());
+    assert_eq!(
+        store.increment("key1".to_owned(), -1),
+        Err(KvsError::Overflow)
+    );
+    assert_eq!(store.get("key1".to_owned()), Some(i64::MIN.to_string()));
+}
+
+// Should accept a leading `+` but not surrounding whitespace
+#[test]
+fn increment_parsing() {
+    let mut store = KvStore::new();
+
+    store.set("key1".to_owned(), "+5".to_owned());
+    assert_eq!(store.increment("key1".to_owned(), 1), Ok(6));
+
+    store.set("key2".to_owned(), " 5".to_owned());
+    assert_eq!(
+        store.increment("key2".to_owned(), 1),
+        Err(KvsError::NotANumber)
+    );
+}
+
+// Should report which keys were removed without stopping at absent ones
+#[test]
+fn remove_batch() {
+    let mut store = KvStore::new();
+
+    store.set("key1".to_owned(), "value1".to_owned());
+    store.set("key3".to_owned(), "value3".to_owned());
+    store.set("key4".to_owned(), "value4".to_owned());
+
+    let removed = store.remove_batch(vec![
+        "key1".to_owned(),
+        "key2".to_owned(),
+        "key3".to_owned(),
+        "key1".to_owned(),
+    ]);
+    assert_eq!(removed, vec![true, false, true, false]);
+
+    assert_eq!(store.get("key1".to_owned()), None);
+    assert_eq!(store.get("key3".to_owned()), None);
+    assert_eq!(store.get("key4".to_owned()), Some("value4".to_owned()));
+}
+
+// A snapshot should keep reading old values after the store changes
+#[test]
+fn snapshot_isolation() {
+    let mut store = KvStore::new();
+
+    store.set("key1".to_owned(), "value1".to_owned());
+    store.set("key2".to_owned(), "value2".to_owned());
+    let snapshot = store.snapshot();
+
+    store.set("key1".to_owned(), "value3".to_owned());
+    store.remove("key2".to_owned());
+    store.set("key3".to_owned(), "value4".to_owned());
+
+    assert_eq!(snapshot.get("key1".to_owned()), Some("value1".to_owned()));
+    assert_eq!(snapshot.get_ref("key2"), Some("value2".to_owned()));
+    assert_eq!(snapshot.get("key3".to_owned()), None);
+    assert_eq!(store.get("key1".to_owned()), Some("value3".to_owned()));
+    assert_eq!(store.get("key2".to_owned()), None);
+}
+
+// Should count plain gets, sets and removes, hits and misses alike
+#[test]
+fn engine_counters_plain_ops() {
+    let mut store = KvStore::new();
+
+    store.set("key1".to_owned(), "value1".to_owned());
+    store.set("key2".to_owned(), "value2".to_owned());
+    store.get("key1".to_owned());
+    store.get("key3".to_owned());
+    store.get_ref("key2");
+    store.remove("key1".to_owned());
+    store.remove("key3".to_owned());
+
+    assert_eq!(
+        store.counters(),
+        EngineCounters {
+            gets: 3,
+            sets: 2,
+            removes: 2,
+        }
+    );
+}
+
+// Should reset all counters to zero
+#[test]
+fn engine_counters_reset() {
+    let mut store = KvStore::new();
+
+    store.set("key1".to_owned(), "value1".to_owned());
+    store.get("key1".to_owned());
+    store.remove("key1".to_owned());
+    store.reset_counters();
+
+    assert_eq!(store.counters(), EngineCounters::default());
+}
+
+// Should count every borrowed lookup as a get
+#[test]
+fn engine_counters_lookups() {
+    let mut store = KvStore::new();
+
+    store.set("key1".to_owned(), "value1".to_owned());
+    store.reset_counters();
+    store.contains_ref("key1");
+    store.value_len("key1");
+    store.get_into("key1", &mut Vec::new());
+    store.get_or("key2".to_owned(), "default".to_owned());
+
+    assert_eq!(
+        store.counters(),
+        EngineCounters {
+            gets: 4,
+            sets: 0,
+            removes: 0,
+        }
+    );
+}
+
+// Should count a read-modify-write as one get plus one set per write
+#[test]
+fn engine_counters_read_modify_write() {
+    let mut store = KvStore::new();
+
+    store.increment("counter".to_owned(), 2).unwrap();
+    store.set_detailed("key1".to_owned(), "value1".to_owned());
+    store.get_or_insert("key2".to_owned(), "value2".to_owned());
+    store.get_or_insert("key2".to_owned(), "value3".to_owned());
+
+    assert_eq!(
+        store.counters(),
+        EngineCounters {
+            gets: 4,
+            sets: 3,
+            removes: 0,
+        }
+    );
+}
+
+// Should count a rename as one remove and one set
+#[test]
+fn engine_counters_rename() {
+    let mut store = KvStore::new();
+
+    store.set("key1".to_owned(), "value1".to_owned());
+    store.reset_counters();
+    store.rename("key1".to_owned(), "key2".to_owned()).unwrap();
+
+    assert_eq!(
+        store.counters(),
+        EngineCounters {
+            gets: 0,
+            sets: 1,
+            removes: 1,
+        }
+    );
+}
+
+// Should count one remove per key removed by the bulk operations
+#[test]
+fn engine_counters_bulk_removals() {
+    let mut store = KvStore::new();
+
+    for key in &["a1", "a2", "b1", "b2", "c1"] {
+        store.set((*key).to_owned(), "value".to_owned());
+    }
+    store.reset_counters();
+    store.remove_batch(vec!["c1".to_owned(), "c2".to_owned()]);
+    store.drain("a".to_owned().."b".to_owned());
+    store.retain(|key, _| key != "b1");
+
+    assert_eq!(
+        store.counters(),
+        EngineCounters {
+            gets: 0,
+            sets: 0,
+            removes: 5,
+        }
+    );
+}
+
+// Should remove and return exactly the keys in the range
+#[test]
+fn drain_range() {
+    let mut store = KvStore::new();
+
+    for i in 0..10 {
+        store.set(format!("key{}", i), format!("value{}", i));
+    }
+
+    let drained = store.drain("key3".to_owned().."key6".to_owned());
+    assert_eq!(
+        drained,
+        vec![
+            ("key3".to_owned(), "value3".to_owned()),
+            ("key4".to_owned(), "value4".to_owned()),
+            ("key5".to_owned(), "value5".to_owned()),
+        ]
+    );
+
+    for i in 0..10 {
+        let value = store.get(format!("key{}", i));
+        if (3..6).contains(&i) {
+            assert_eq!(value, None);
+        } else {
+            assert_eq!(value, Some(format!("value{}", i)));
+        }
+    }
+}
+
+// Should copy values into a reused buffer and clear it on a miss
+#[test]
+fn get_into_buffer() {
+    let mut store = KvStore::new();
+
+    store.set("key1".to_owned(), "value1".to_owned());
+    store.set("key2".to_owned(), "v2".to_owned());
+
+    let mut buf = Vec::new();
+    assert!(store.get_into("key1", &mut buf));
+    assert_eq!(buf, b"value1");
+    assert!(store.get_into("key2", &mut buf));
+    assert_eq!(buf, b"v2");
+    assert!(!store.get_into("key3", &mut buf));
+    assert!(buf.is_empty());
+}
+
+// Should drop only the key/values rejected by the predicate
+#[test]
+fn retain_with_predicate() {
+    let mut store = KvStore::new();
+
+    store.set("tmp:key1".to_owned(), "value1".to_owned());
+    store.set("tmp:key2".to_owned(), "value2".to_owned());
+    store.set("key3".to_owned(), "value3".to_owned());
+
+    store.retain(|key, _| !key.starts_with("tmp:"));
+
+    assert_eq!(store.get("tmp:key1".to_owned()), None);
+    assert_eq!(store.get("tmp:key2".to_owned()), None);
+    assert_eq!(store.get("key3".to_owned()), Some("value3".to_owned()));
+}
+
+// Should report `Created` on first insert and `Updated` on overwrite
+#[test]
+fn set_detailed_outcome() {
+    let mut store = KvStore::new();
+
+    assert_eq!(
+        store.set_detailed("key1".to_owned(), "value1".to_owned()),
+        SetOutcome::Created
+    );
+    assert_eq!(
+        store.set_detailed("key1".to_owned(), "v2".to_owned()),
+        SetOutcome::Updated { previous_len: 6 }
+    );
+    assert_eq!(store.get("key1".to_owned()), Some("v2".to_owned()));
+}
+
+// Keys and values with newlines, tabs and NULs should round-trip unchanged
+#[test]
+fn control_characters_round_trip() {
+    let mut store = KvStore::new();
+    let key = "key\n\t\0".to_owned();
+    let value = "value\n\t\0".to_owned();
+
+    store.set(key.clone(), value.clone());
+    assert_eq!(store.get(key), Some(value));
+}
+
+// Should scan from the highest key down, honouring the range bounds
+#[test]
+fn scan_rev_range() {
+    let mut store = KvStore::new();
+
+    for i in 0..10 {
+        store.set(format!("k{}", i), format!("v{}", i));
+    }
+
+    let keys: Vec<String> = store.scan_rev(..).map(|(key, _)| key).collect();
+    let expected: Vec<String> = (0..10).rev().map(|i| format!("k{}", i)).collect();
+    assert_eq!(keys, expected);
+
+    let entries: Vec<(String, String)> =
+        store.scan_rev("k3".to_owned()..="k6".to_owned()).collect();
+    assert_eq!(
+        entries,
+        vec![
+            ("k6".to_owned(), "v6".to_owned()),
+            ("k5".to_owned(), "v5".to_owned()),
+            ("k4".to_owned(), "v4".to_owned()),
+            ("k3".to_owned(), "v3".to_owned()),
+        ]
+    );
+}
+
+// Paging through the store should return every key once, in order
+#[test]
+fn scan_paginated_pages() {
+    let mut store = KvStore::new();
+
+    for i in 0..1000 {
+        store.set(format!("key{:04}", i), format!("value{}", i));
+    }
+
+    let mut pages = 0;
+    let mut keys = Vec::new();
+    let mut cursor = None;
+    loop {
+        let ScanPage {
+            entries,
+            next_cursor,
+        } = store.scan_paginated(.., cursor, 100);
+        assert_eq!(entries.len(), 100);
+        pages += 1;
+        keys.extend(entries.into_iter().map(|(key, _)| key));
+        cursor = next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+
+    let expected: Vec<String> = (0..1000).map(|i| format!("key{:04}", i)).collect();
+    assert_eq!(pages, 10);
+    assert_eq!(keys, expected);
+}
+
+// Paging should stay within the range bounds
+#[test]
+fn scan_paginated_range() {
+    let mut store = KvStore::new();
+
+    for i in 0..10 {
+        store.set(format!("k{}", i), format!("v{}", i));
+    }
+
+    let page = store.scan_paginated("k2".to_owned().."k5".to_owned(), None, 2);
+    assert_eq!(
+        page.entries,
+        vec![
+            ("k2".to_owned(), "v2".to_owned()),
+            ("k3".to_owned(), "v3".to_owned()),
+        ]
+    );
+    assert_eq!(page.next_cursor, Some("k3".to_owned()));
+
+    let page = store.scan_paginated("k2".to_owned().."k5".to_owned(), page.next_cursor, 2);
+    assert_eq!(page.entries, vec![("k4".to_owned(), "v4".to_owned())]);
+    assert_eq!(page.next_cursor, None);
+}